@@ -1,7 +1,18 @@
 #![recursion_limit = "1024"]
 use cairo;
+use futures::channel::oneshot;
 use ngspice::{Callbacks, NgSpice, NgSpiceError, Simulator};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use steel::steel_vm::engine::Engine;
 use vgtk::ext::*;
 use vgtk::lib::gdk_pixbuf::Pixbuf;
 //use vgtk::lib::gdk::EventMask;
@@ -18,13 +29,511 @@ static V: &[u8] = include_bytes!("img/v.svg");
 static I: &[u8] = include_bytes!("img/i.svg");
 static R: &[u8] = include_bytes!("img/r.svg");
 
+/// Shared ring buffer of ngspice console lines, capped so a chatty simulation
+/// can't grow it without bound.
+type Log = Arc<Mutex<VecDeque<String>>>;
+
+const LOG_CAPACITY: usize = 1000;
+
+/// Append a line to the log, dropping the oldest once it is full.
+fn log_push(log: &Log, line: String) {
+    let mut log = log.lock().unwrap();
+    log.push_back(line);
+    while log.len() > LOG_CAPACITY {
+        log.pop_front();
+    }
+}
+
 #[derive(Clone, Debug)]
-struct Cb {}
+struct Cb {
+    log: Log,
+}
 
 impl Callbacks for Cb {
     fn send_char(&mut self, s: &str) {
-        print!("{}\n", s);
+        log_push(&self.log, s.to_string());
+    }
+}
+
+/// A request for the simulation worker thread. Every variant that produces
+/// data carries a `oneshot` sender so the caller is handed exactly the result
+/// of *its* submission; dropping the receiver lets a superseded sweep fall on
+/// the floor without blocking the worker.
+enum SimCommand {
+    Alter { key: String, val: String },
+    Op { reply: oneshot::Sender<SimResult> },
+    Tran { step: String, stop: String, start: String, reply: oneshot::Sender<SimResult> },
+    Ac { points: String, fstart: String, fstop: String, reply: oneshot::Sender<SimResult> },
+    /// Evaluate a Scheme thunk (by name) and return whatever the script left in
+    /// the shared results map; this is how param edits drive the simulation
+    /// once a script is loaded.
+    Eval { thunk: String, reply: oneshot::Sender<SimResult> },
+}
+
+/// A decoded analysis result. Each signal keeps its full sample vector (a
+/// single sample for an operating point, the whole sweep for `.tran`/`.ac`);
+/// `sweep` is the shared x-axis — time for transient, frequency for AC, empty
+/// for `.op`. AC data is stored as magnitude, with phase under a `"<sig> phase"`
+/// key so complex results are no longer discarded.
+#[derive(Clone, Debug, Default)]
+struct SimResult {
+    data: HashMap<String, Vec<f64>>,
+    sweep: Vec<f64>,
+}
+
+impl SimResult {
+    /// Last sample of a signal, for the scalar readouts that show a single
+    /// operating-point number.
+    fn scalar(&self, key: &str) -> f64 {
+        self.data.get(key).and_then(|v| v.last()).copied().unwrap_or(0.0)
+    }
+}
+
+/// Decode an ngspice vector set, keeping real vectors verbatim and splitting
+/// complex AC vectors into magnitude and phase. The sweep axis is taken from
+/// the real vector named `time` or `frequency`, if there is one.
+fn decode(results: &ngspice::Simulation) -> SimResult {
+    let mut data = HashMap::new();
+    let mut sweep = Vec::new();
+    for (k, v) in results.data.iter() {
+        match v.data {
+            ngspice::ComplexSlice::Real(nums) => {
+                let samples = nums.to_vec();
+                if k == "time" || k == "frequency" {
+                    sweep = samples.clone();
+                }
+                data.insert(k.clone(), samples);
+            }
+            ngspice::ComplexSlice::Complex(nums) => {
+                data.insert(
+                    k.clone(),
+                    nums.iter().map(|c| (c.re * c.re + c.im * c.im).sqrt()).collect(),
+                );
+                data.insert(
+                    format!("{} phase", k),
+                    nums.iter().map(|c| c.im.atan2(c.re)).collect(),
+                );
+            }
+        }
+    }
+    SimResult { data, sweep }
+}
+
+/// The configuration a plugin reports during the JSON-RPC handshake: a symbol
+/// to drop in the palette, the SVG to draw it with, the parameter keys the user
+/// may edit, and the SPICE lines to splice into the netlist.
+#[derive(Clone, Debug, Deserialize)]
+struct PluginConfig {
+    symbol: String,
+    svg: String,
+    params: Vec<String>,
+    /// Model-card lines (`.MODEL ...`) emitted verbatim before `.end`.
+    model: Vec<String>,
+    /// Instance line that realises the device in the netlist.
+    instance: String,
+}
+
+/// A discovered device plugin: the long-lived child process we talk JSON-RPC
+/// to, its reported configuration, and the rendered symbol.
+struct Plugin {
+    config: PluginConfig,
+    pixbuf: Pixbuf,
+    /// Behind `Arc<Mutex<..>>` (not `Rc<RefCell<..>>`) so the blocking JSON-RPC
+    /// round-trip can be driven from a background thread, off the GTK loop.
+    io: Arc<Mutex<PluginIo>>,
+}
+
+struct PluginIo {
+    #[allow(dead_code)]
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl PluginIo {
+    /// Send a JSON-RPC request and block for the response with the matching
+    /// `id`, skipping any notifications or log lines the plugin interleaves on
+    /// stdout. Returns `None` if the request cannot be written, on a read error
+    /// or EOF, or when the matching response has no `result`. There is no
+    /// timeout: a plugin that keeps stdout open without answering blocks the call.
+    fn call(&mut self, method: &str, params: serde_json::Value) -> Option<serde_json::Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let req = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        writeln!(self.stdin, "{}", req).ok()?;
+        self.stdin.flush().ok()?;
+        loop {
+            let mut line = String::new();
+            // `read_line` returning 0 means the child closed its stdout (EOF).
+            if self.stdout.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let resp: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(resp) => resp,
+                Err(_) => continue,
+            };
+            if resp.get("id").and_then(|v| v.as_u64()) == Some(id) {
+                return resp.get("result").cloned();
+            }
+        }
+    }
+}
+
+impl Plugin {
+    /// Spawn `path` and perform the `describe` handshake, decoding the reported
+    /// SVG into a `Pixbuf` the same way `Model::default` decodes the built-ins.
+    fn spawn(path: &std::path::Path) -> Option<Plugin> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut io = PluginIo {
+            stdin: child.stdin.take()?,
+            stdout: BufReader::new(child.stdout.take()?),
+            child,
+            next_id: 1,
+        };
+        let config: PluginConfig =
+            serde_json::from_value(io.call("describe", json!({}))?).ok()?;
+        let data_stream = MemoryInputStream::from_bytes(&Bytes::from(config.svg.as_bytes()));
+        let pixbuf = Pixbuf::from_stream(&data_stream, None as Option<&Cancellable>).ok()?;
+        Some(Plugin { config, pixbuf, io: Arc::new(Mutex::new(io)) })
+    }
+
+    /// Does this plugin own `key`? True when `key` is exactly one of the
+    /// parameter keys the plugin reported in its handshake.
+    fn owns(&self, key: &str) -> bool {
+        self.config.params.iter().any(|p| p == key)
+    }
+
+    /// Let the plugin validate/transform a value before it reaches `alter`,
+    /// running the blocking round-trip on a background thread so an unresponsive
+    /// plugin cannot stall the event loop. The returned future resolves with the
+    /// transformed value, falling back to the raw value if the plugin declines.
+    fn transform(&self, key: &str, val: &str) -> oneshot::Receiver<String> {
+        let io = self.io.clone();
+        let (key, val) = (key.to_string(), val.to_string());
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            let out = io
+                .lock()
+                .unwrap()
+                .call("set", json!({"key": &key, "value": &val}))
+                .and_then(|v| v.as_str().map(String::from))
+                .unwrap_or(val);
+            let _ = tx.send(out);
+        });
+        rx
+    }
+
+    /// The netlist lines this plugin contributes: its model cards and instance.
+    fn netlist(&self) -> Vec<String> {
+        let mut lines = self.config.model.clone();
+        lines.push(self.config.instance.clone());
+        lines
+    }
+}
+
+/// Discover plugins by spawning every executable in `./plugins`. Missing
+/// directory or a plugin that fails its handshake is simply skipped.
+fn discover_plugins() -> Vec<Plugin> {
+    let mut plugins = Vec::new();
+    if let Ok(entries) = std::fs::read_dir("plugins") {
+        for entry in entries.flatten() {
+            if let Some(plugin) = Plugin::spawn(&entry.path()) {
+                plugins.push(plugin);
+            }
+        }
     }
+    plugins
+}
+
+/// Build a Steel engine whose primitives are bound to the worker's `NgSpice`
+/// handle and its results map. `op` (and any other analysis primitive) leaves
+/// its decoded output in `results` so both the `result` accessor and the Rust
+/// side that drains the worker can read it.
+fn build_engine(
+    spice: Arc<NgSpice<Cb>>,
+    results: Rc<RefCell<SimResult>>,
+    plugin_lines: Vec<String>,
+) -> Engine {
+    let mut engine = Engine::new();
+
+    let sp = spice.clone();
+    engine.register_fn("circuit", move |lines: Vec<String>| {
+        // Splice plugin-contributed model cards and instances in just before
+        // `.end` so discovered devices become part of the simulated netlist.
+        let mut all: Vec<String> = Vec::new();
+        for line in &lines {
+            if line.trim() == ".end" {
+                all.extend(plugin_lines.iter().cloned());
+            }
+            all.push(line.clone());
+        }
+        let refs: Vec<&str> = all.iter().map(|s| s.as_str()).collect();
+        sp.circuit(&refs).is_ok()
+    });
+
+    let sp = spice.clone();
+    engine.register_fn("alter", move |name: String, val: String| {
+        sp.command(&format!("alter {}={}", name, val)).is_ok()
+    });
+
+    let sp = spice.clone();
+    let res = results.clone();
+    engine.register_fn("op", move || {
+        if let Ok(sim) = sp.op() {
+            *res.borrow_mut() = decode(&sim);
+            true
+        } else {
+            false
+        }
+    });
+
+    let res = results.clone();
+    engine.register_fn("result", move |key: String| -> f64 { res.borrow().scalar(&key) });
+
+    engine
+}
+
+/// Spawn the long-lived thread that owns the `NgSpice` instance and the Steel
+/// engine driving it. The script is evaluated once to build the circuit, then
+/// each turn the worker coalesces everything currently queued: all `Alter`s are
+/// applied in order, but only the most recent analysis/`Eval` is actually run,
+/// so a burst of rapid param edits during a long sweep collapses to a single
+/// sweep against the final netlist and the superseded ones are dropped (which
+/// also cancels their awaiting futures).
+fn spawn_worker(script: String, plugin_lines: Vec<String>, log: Log) -> Sender<SimCommand> {
+    let (tx, rx) = channel::<SimCommand>();
+    thread::spawn(move || {
+        let spice = NgSpice::new(Cb { log: log.clone() }).unwrap();
+        let results = Rc::new(RefCell::new(SimResult::default()));
+        let mut engine = build_engine(spice.clone(), results.clone(), plugin_lines);
+        engine.run(&script).expect("script failed");
+        // Reply with an analysis outcome: decode it on success, and on failure
+        // note it in the log and still reply with an empty result so the UI
+        // re-renders and shows why.
+        let finish = |reply: oneshot::Sender<SimResult>, outcome: Result<_, _>, what: &str| {
+            match outcome {
+                Ok(sim) => {
+                    let _ = reply.send(decode(&sim));
+                }
+                Err(_) => {
+                    log_push(&log, format!("{} failed", what));
+                    let _ = reply.send(SimResult::default());
+                }
+            }
+        };
+        // `command` only returns ngspice's status messages, not data; the sweep
+        // vectors live in the current plot, so run the analysis and then read the
+        // plot back the same way `op()` does internally.
+        let analyze = |cmd: &str| spice.command(cmd).and_then(|_| spice.current_plot());
+        for first in rx {
+            // Drain whatever else is already queued so a storm of edits collapses
+            // into one pass.
+            let mut batch = vec![first];
+            while let Ok(cmd) = rx.try_recv() {
+                batch.push(cmd);
+            }
+            let mut analysis: Option<SimCommand> = None;
+            for cmd in batch {
+                match cmd {
+                    SimCommand::Alter { key, val } => {
+                        let _ = spice.command(&format!("alter {}={}", key, val));
+                    }
+                    // Keep only the latest; replacing `analysis` drops the previous
+                    // reply sender and so cancels the sweep no one is waiting for.
+                    other => analysis = Some(other),
+                }
+            }
+            match analysis {
+                Some(SimCommand::Op { reply }) => {
+                    if !reply.is_canceled() {
+                        finish(reply, spice.op(), "op");
+                    }
+                }
+                Some(SimCommand::Tran { step, stop, start, reply }) => {
+                    if !reply.is_canceled() {
+                        finish(reply, analyze(&format!("tran {} {} {}", step, stop, start)), "tran");
+                    }
+                }
+                Some(SimCommand::Ac { points, fstart, fstop, reply }) => {
+                    if !reply.is_canceled() {
+                        finish(reply, analyze(&format!("ac dec {} {} {}", points, fstart, fstop)), "ac");
+                    }
+                }
+                Some(SimCommand::Eval { thunk, reply }) => {
+                    if reply.is_canceled() {
+                        // superseded before we got to it
+                    } else if engine.run(&format!("({})", thunk)).is_ok() {
+                        let _ = reply.send(results.borrow().clone());
+                    } else {
+                        log_push(&log, format!("({}) failed", thunk));
+                        let _ = reply.send(SimResult::default());
+                    }
+                }
+                Some(SimCommand::Alter { .. }) | None => {}
+            }
+        }
+    });
+    tx
+}
+
+/// Handle onto the simulation worker. Cloneable so it can live inside the
+/// cloneable `Model`; every clone talks to the same thread over the channel.
+#[derive(Clone)]
+struct SimClient {
+    tx: Sender<SimCommand>,
+    /// Captured ngspice console output, shared with the GUI log panel.
+    log: Log,
+}
+
+impl SimClient {
+    /// Start the worker with the given Scheme program driving the netlist and
+    /// any plugin-contributed lines spliced in.
+    fn new(script: String, plugin_lines: Vec<String>) -> SimClient {
+        let log: Log = Arc::new(Mutex::new(VecDeque::new()));
+        SimClient { tx: spawn_worker(script, plugin_lines, log.clone()), log }
+    }
+
+    /// Fire-and-forget submission used from the event loop so a param edit
+    /// never blocks on the simulator.
+    fn submit(&self, cmd: SimCommand) {
+        let _ = self.tx.send(cmd);
+    }
+
+    /// Evaluate a script thunk and hand back a future that resolves with the
+    /// results it produced, for use with `UpdateAction::defer`.
+    fn eval(&self, thunk: &str) -> oneshot::Receiver<SimResult> {
+        let (reply, rx) = oneshot::channel();
+        self.submit(SimCommand::Eval { thunk: thunk.to_string(), reply });
+        rx
+    }
+
+    /// Synchronous thunk evaluation, used at startup and when a project is
+    /// opened; blocks the caller until the worker replies.
+    fn eval_blocking(&self, thunk: &str) -> SimResult {
+        futures::executor::block_on(self.eval(thunk)).unwrap_or_default()
+    }
+
+    /// Run a transient sweep, returning a future over the vector results.
+    fn tran(&self) -> oneshot::Receiver<SimResult> {
+        let (reply, rx) = oneshot::channel();
+        self.submit(SimCommand::Tran {
+            step: String::from("1u"),
+            stop: String::from("1m"),
+            start: String::from("0"),
+            reply,
+        });
+        rx
+    }
+
+    /// Run an AC sweep (decade points), returning a future over the results.
+    fn ac(&self) -> oneshot::Receiver<SimResult> {
+        let (reply, rx) = oneshot::channel();
+        self.submit(SimCommand::Ac {
+            points: String::from("10"),
+            fstart: String::from("1"),
+            fstop: String::from("1g"),
+            reply,
+        });
+        rx
+    }
+}
+
+/// Which analysis the UI currently drives and plots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AnalysisMode {
+    Op,
+    Tran,
+    Ac,
+}
+
+/// A single straight wire drawn on the schematic. Replaces the fixed
+/// `move_to`/`line_to` sequence so saved documents render their own routing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WireSegment {
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+}
+
+/// A symbol placed on the canvas, captured for round-tripping through a saved
+/// project. `kind` names one of the built-in/plugin symbols.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Symbol {
+    kind: String,
+    x: i32,
+    y: i32,
+}
+
+/// Everything about a schematic that survives a restart: the netlist, the
+/// editable parameters, the wire routing, and the placed symbols.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Document {
+    circuit: Vec<String>,
+    params: HashMap<String, String>,
+    wires: Vec<WireSegment>,
+    symbols: Vec<Symbol>,
+}
+
+/// The built-in schematic, used when Mosaic starts without a project file.
+impl Default for Document {
+    fn default() -> Document {
+        let circuit = [
+            ".title my awesome schematic",
+            ".MODEL FAKE_NMOS NMOS (LEVEL=3 VTO=0.75)",
+            ".save all @m1[gm] @m1[id] @m1[vgs] @m1[vds] @m1[vto]",
+            "R1 /vdd /drain 10k",
+            "M1 /drain /gate GND GND FAKE_NMOS W=10u L=1u",
+            "V1 /vdd GND dc(5)",
+            "V2 /gate GND dc(2)",
+            ".end",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let mut params = HashMap::new();
+        params.insert(String::from("v1 dc"), String::from("5"));
+        params.insert(String::from("v2 dc"), String::from("2"));
+        params.insert(String::from("r1"), String::from("1k"));
+        params.insert(String::from("m1 w"), String::from("10u"));
+        params.insert(String::from("m1 l"), String::from("1u"));
+        let wire = |x1, y1, x2, y2| WireSegment { x1, y1, x2, y2 };
+        let wires = vec![
+            wire(45., 400., 45., 245.),
+            wire(45., 205., 45., 40.),
+            wire(45., 40., 310., 40.),
+            wire(310., 40., 310., 60.),
+            wire(310., 140., 310., 220.),
+            wire(310., 340., 310., 400.),
+            wire(310., 400., 45., 400.),
+            wire(125., 400., 125., 360.),
+            wire(125., 330., 125., 300.),
+            wire(125., 300., 245., 300.),
+        ];
+        // The statically placed device symbols, matching the built-in layout.
+        let symbols = vec![
+            Symbol { kind: String::from("nmos"), x: 220, y: 200 },
+            Symbol { kind: String::from("r"), x: 300, y: 50 },
+            Symbol { kind: String::from("v"), x: 20, y: 200 },
+            Symbol { kind: String::from("v"), x: 100, y: 320 },
+        ];
+        Document { circuit, params, wires, symbols }
+    }
+}
+
+/// The bundled Scheme program loaded when no project or `.scm` is supplied.
+static DEFAULT_SCRIPT: &str = include_str!("default.scm");
+
+/// Render a document's circuit lines as a Scheme program the worker can load,
+/// reusing the same `circuit`/`op` primitives as a hand-written script.
+fn script_for(circuit: &[String]) -> String {
+    let lines: Vec<String> = circuit.iter().map(|l| format!("    {:?}", l)).collect();
+    format!("(circuit (list\n{}))\n(define (run) (op))\n", lines.join("\n"))
 }
 
 #[derive(Clone)]
@@ -35,9 +544,13 @@ struct Model {
     i_small: Pixbuf,
     r: Pixbuf,
     r_small: Pixbuf,
-    params: HashMap<String, String>,
-    results: HashMap<String, f64>,
-    spice: std::sync::Arc<NgSpice<Cb>>,
+    doc: Document,
+    results: SimResult,
+    mode: AnalysisMode,
+    /// Signals the user has selected to plot in the waveform area.
+    plot: Vec<String>,
+    sim: SimClient,
+    plugins: Rc<Vec<Plugin>>,
 }
 
 impl Default for Model {
@@ -54,33 +567,17 @@ impl Default for Model {
         let r = Pixbuf::from_stream(&data_stream, None as Option<&Cancellable>).unwrap();
         data_stream.seek(0, SeekType::Set, None as Option<&Cancellable>).unwrap();
         let r_small = Pixbuf::from_stream_at_scale(&data_stream, 40, 40, true, None as Option<&Cancellable>).unwrap();
-        let mut params = HashMap::new();
-        params.insert(String::from("v1 dc"), String::from("5"));
-        params.insert(String::from("v2 dc"), String::from("2"));
-        params.insert(String::from("r1"), String::from("1k"));
-        params.insert(String::from("m1 w"), String::from("10u"));
-        params.insert(String::from("m1 l"), String::from("1u"));
-
-        let spice = NgSpice::new(Cb {}).unwrap();
-        spice.circuit(&[
-                ".title my awesome schematic",
-                ".MODEL FAKE_NMOS NMOS (LEVEL=3 VTO=0.75)",
-                ".save all @m1[gm] @m1[id] @m1[vgs] @m1[vds] @m1[vto]",
-                "R1 /vdd /drain 10k",
-                "M1 /drain /gate GND GND FAKE_NMOS W=10u L=1u",
-                "V1 /vdd GND dc(5)",
-                "V2 /gate GND dc(2)",
-                ".end",
-            ]).expect("circuit failed");
-        let results = spice.op().expect("op failed");
-        let results = results.data.iter().map(|(k, v)| {
-            if let ngspice::ComplexSlice::Real(num) = v.data {
-                (k.clone(), num.first().unwrap_or(&0.0).clone())
-            } else {
-                (k.clone(), 0.0)
-            }
-        }
-        ).collect();
+        let doc = Document::default();
+        // A `.scm` passed on the command line overrides the built-in script;
+        // otherwise the bundled `default.scm` drives the schematic.
+        let script = std::env::args()
+            .nth(1)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_else(|| DEFAULT_SCRIPT.to_string());
+        let plugins = discover_plugins();
+        let plugin_lines: Vec<String> = plugins.iter().flat_map(Plugin::netlist).collect();
+        let sim = SimClient::new(script, plugin_lines);
+        let results = sim.eval_blocking("run");
         Model {
             nmos: nmos,
             open_small: open_small,
@@ -88,9 +585,12 @@ impl Default for Model {
             i_small: i_small,
             r: r,
             r_small: r_small,
-            params: params,
+            doc: doc,
             results: results,
-            spice: spice,
+            mode: AnalysisMode::Op,
+            plot: vec![String::from("@m1[id]")],
+            sim: sim,
+            plugins: Rc::new(plugins),
         }
     }
 }
@@ -99,6 +599,14 @@ impl Default for Model {
 enum Message {
     Exit,
     ParamChange(String, String),
+    /// A param edit finished its (possibly plugin-transformed) round-trip: commit
+    /// the final value, plus the results when the sweep wasn't superseded.
+    Applied { key: String, val: String, result: Option<SimResult> },
+    SimDone(SimResult),
+    RunAnalysis(AnalysisMode),
+    ToggleSignal(String),
+    Open,
+    Save,
     Coord(i64),
     None,
 }
@@ -109,29 +617,150 @@ impl IntoSignalReturn<Inhibit> for Message {
     }
 }
 
-fn draw_layout( _l: &Layout, cr: &cairo::Context) {
+fn draw_layout(wires: &[WireSegment], cr: &cairo::Context) {
     cr.set_line_width(1.5);
     cr.set_source_rgb(0., 0., 0.);
-    cr.move_to(45., 400.);
-    cr.line_to(45., 245.);
-    cr.move_to(45., 205.);
-    cr.line_to(45., 40.);
-    cr.line_to(310., 40.);
-    cr.line_to(310., 60.);
-    cr.move_to(310., 140.);
-    cr.line_to(310., 220.);
-    cr.move_to(310., 340.);
-    cr.line_to(310., 400.);
-    cr.line_to(45., 400.);
-    cr.move_to(125., 400.);
-    cr.line_to(125., 360.);
-    cr.move_to(125., 330.);
-    cr.line_to(125., 300.);
-    cr.line_to(245., 300.);
+    for w in wires {
+        cr.move_to(w.x1, w.y1);
+        cr.line_to(w.x2, w.y2);
+    }
     cr.stroke();
     //Inhibit(false)
 }
 
+/// Pop up a native file chooser and return the selected path, if any.
+fn choose_file(action: FileChooserAction) -> Option<std::path::PathBuf> {
+    let dialog = FileChooserDialog::with_buttons(
+        Some("Project"),
+        None as Option<&Window>,
+        action,
+        &[
+            ("_Cancel", ResponseType::Cancel),
+            ("_Ok", ResponseType::Ok),
+        ],
+    );
+    let path = if dialog.run() == ResponseType::Ok {
+        dialog.get_filename()
+    } else {
+        None
+    };
+    dialog.close();
+    path
+}
+
+/// Kick off the analysis matching `mode` and hand back the results future.
+fn run_mode(sim: &SimClient, mode: AnalysisMode) -> oneshot::Receiver<SimResult> {
+    match mode {
+        AnalysisMode::Op => sim.eval("run"),
+        AnalysisMode::Tran => sim.tran(),
+        AnalysisMode::Ac => sim.ac(),
+    }
+}
+
+/// Draw the selected signals as auto-scaled line plots, in the same immediate
+/// `on draw` style as `draw_layout`: axes and gridlines first, then a polyline
+/// per signal. Falls back to plotting against sample index when there is no
+/// sweep axis (e.g. an operating point).
+fn draw_plot(result: &SimResult, signals: &[String], cr: &cairo::Context, w: f64, h: f64) {
+    let (ox, oy) = (40.0, 10.0);
+    let (pw, ph) = (w - ox - 10.0, h - oy - 25.0);
+
+    // Collect the series actually present so bounds match what we draw.
+    let series: Vec<&Vec<f64>> = signals.iter().filter_map(|s| result.data.get(s)).collect();
+    let n = series.iter().map(|s| s.len()).max().unwrap_or(0);
+    if n == 0 {
+        return;
+    }
+    let xs: Vec<f64> = if result.sweep.len() == n {
+        result.sweep.clone()
+    } else {
+        (0..n).map(|i| i as f64).collect()
+    };
+
+    let (xmin, xmax) = (xs.first().copied().unwrap_or(0.0), xs.last().copied().unwrap_or(1.0));
+    let mut ymin = f64::INFINITY;
+    let mut ymax = f64::NEG_INFINITY;
+    for s in &series {
+        for &y in s.iter() {
+            ymin = ymin.min(y);
+            ymax = ymax.max(y);
+        }
+    }
+    if !(ymin.is_finite() && ymax.is_finite()) {
+        return;
+    }
+    if (ymax - ymin).abs() < f64::EPSILON {
+        ymin -= 1.0;
+        ymax += 1.0;
+    }
+    let xspan = if (xmax - xmin).abs() < f64::EPSILON { 1.0 } else { xmax - xmin };
+    let yspan = ymax - ymin;
+    let px = |x: f64| ox + (x - xmin) / xspan * pw;
+    let py = |y: f64| oy + (1.0 - (y - ymin) / yspan) * ph;
+
+    // Gridlines.
+    cr.set_line_width(0.5);
+    cr.set_source_rgb(0.85, 0.85, 0.85);
+    for i in 0..=4 {
+        let gy = oy + ph * i as f64 / 4.0;
+        cr.move_to(ox, gy);
+        cr.line_to(ox + pw, gy);
+        let gx = ox + pw * i as f64 / 4.0;
+        cr.move_to(gx, oy);
+        cr.line_to(gx, oy + ph);
+    }
+    cr.stroke();
+
+    // Axes.
+    cr.set_line_width(1.0);
+    cr.set_source_rgb(0., 0., 0.);
+    cr.move_to(ox, oy);
+    cr.line_to(ox, oy + ph);
+    cr.line_to(ox + pw, oy + ph);
+    cr.stroke();
+
+    // One polyline per signal, cycling through a small palette.
+    let palette = [(0.8, 0.1, 0.1), (0.1, 0.3, 0.8), (0.1, 0.6, 0.2)];
+    for (i, s) in series.iter().enumerate() {
+        let (r, g, b) = palette[i % palette.len()];
+        cr.set_source_rgb(r, g, b);
+        cr.set_line_width(1.5);
+        for (j, &y) in s.iter().enumerate() {
+            let x = px(*xs.get(j).unwrap_or(&(j as f64)));
+            if j == 0 {
+                cr.move_to(x, py(y));
+            } else {
+                cr.line_to(x, py(y));
+            }
+        }
+        cr.stroke();
+    }
+}
+
+impl Model {
+    /// Resolve a symbol kind to its rendered pixbuf.
+    fn pixbuf_for(&self, kind: &str) -> Pixbuf {
+        match kind {
+            "nmos" => self.nmos.clone(),
+            "r" => self.r.clone(),
+            "v" => self.v.clone(),
+            _ => self
+                .plugins
+                .iter()
+                .find(|p| p.config.symbol == kind)
+                .map(|p| p.pixbuf.clone())
+                .unwrap_or_else(|| self.open_small.clone()),
+        }
+    }
+
+    /// Render a placed symbol at its saved `Layout::x/y`, so documents round-trip
+    /// their component placement rather than relying on hardcoded positions.
+    fn render_symbol(&self, sym: &Symbol) -> VNode<Model> {
+        let pixbuf = self.pixbuf_for(&sym.kind);
+        gtk! { <Image Layout::x=sym.x Layout::y=sym.y pixbuf=Some(pixbuf)/> }
+    }
+}
+
 impl Component for Model {
     type Message = Message;
     type Properties = ();
@@ -143,20 +772,80 @@ impl Component for Model {
                 UpdateAction::None
             }
             Message::ParamChange(key, val) => {
-                let cmd = format!("alter {}={}", &key, &val);
-                self.params.insert(key, val);
-                if self.spice.command(&cmd).is_err() {
-                    return UpdateAction::None;
+                // An owning plugin may validate/transform the value, but that is a
+                // blocking round-trip to a subprocess, so do it off-thread along
+                // with the sweep. If a later edit's analysis reaches the worker
+                // in the same batch, the worker drops this edit's reply sender
+                // and only the later sweep is run.
+                let transform = self.plugins.iter().find(|p| p.owns(&key)).map(|p| p.transform(&key, &val));
+                let sim = self.sim.clone();
+                let mode = self.mode;
+                UpdateAction::defer(async move {
+                    let val = match transform {
+                        Some(rx) => rx.await.unwrap_or(val),
+                        None => val,
+                    };
+                    sim.submit(SimCommand::Alter { key: key.clone(), val: val.clone() });
+                    // Always commit the param, even if this sweep is superseded
+                    // (its reply dropped → `Err`); only the results are optional,
+                    // so the entry fields never drift from the simulated netlist.
+                    let result = run_mode(&sim, mode).await.ok();
+                    Message::Applied { key, val, result }
+                })
+            }
+            Message::Applied { key, val, result } => {
+                self.doc.params.insert(key, val);
+                if let Some(result) = result {
+                    self.results = result;
                 }
-                if let Ok(results) = self.spice.op() {
-                    self.results = results.data.iter().map(|(k, v)| {
-                        if let ngspice::ComplexSlice::Real(num) = v.data {
-                            (k.clone(), num.first().unwrap_or(&0.0).clone())
-                        } else {
-                            (k.clone(), 0.0)
-                        }
+                UpdateAction::Render
+            }
+            Message::SimDone(res) => {
+                self.results = res;
+                UpdateAction::Render
+            }
+            Message::RunAnalysis(mode) => {
+                self.mode = mode;
+                let op = run_mode(&self.sim, mode);
+                UpdateAction::defer(async move {
+                    match op.await {
+                        Ok(res) => Message::SimDone(res),
+                        Err(_) => Message::None,
+                    }
+                })
+            }
+            Message::ToggleSignal(sig) => {
+                if let Some(i) = self.plot.iter().position(|s| s == &sig) {
+                    self.plot.remove(i);
+                } else {
+                    self.plot.push(sig);
+                }
+                UpdateAction::Render
+            }
+            Message::Save => {
+                if let Some(path) = choose_file(FileChooserAction::Save) {
+                    if let Ok(json) = serde_json::to_string_pretty(&self.doc) {
+                        let _ = std::fs::write(path, json);
+                    }
+                }
+                UpdateAction::None
+            }
+            Message::Open => {
+                if let Some(doc) = choose_file(FileChooserAction::Open)
+                    .and_then(|path| std::fs::read_to_string(path).ok())
+                    .and_then(|text| serde_json::from_str::<Document>(&text).ok())
+                {
+                    // Rebuild the simulation against the loaded netlist, replay the
+                    // saved parameter edits (the circuit lines keep their original
+                    // inline values), then re-run the operating point so the
+                    // readouts reflect what was saved.
+                    let plugin_lines = self.plugins.iter().flat_map(Plugin::netlist).collect();
+                    self.sim = SimClient::new(script_for(&doc.circuit), plugin_lines);
+                    for (key, val) in &doc.params {
+                        self.sim.submit(SimCommand::Alter { key: key.clone(), val: val.clone() });
                     }
-                    ).collect();
+                    self.results = self.sim.eval_blocking("run");
+                    self.doc = doc;
                     return UpdateAction::Render;
                 }
                 UpdateAction::None
@@ -166,16 +855,28 @@ impl Component for Model {
     }
 
     fn view(&self) -> VNode<Model> {
-        let vgs = *self.results.get("@m1[vgs]").unwrap_or(&0.);
-        let vds = *self.results.get("@m1[vds]").unwrap_or(&0.);
+        let vgs = self.results.scalar("@m1[vgs]");
+        let vds = self.results.scalar("@m1[vds]");
         let vth = 0.75;
+        // Snapshot the captured ngspice output for the console panel.
+        let console = TextBuffer::new(None as Option<&TextTagTable>);
+        console.set_text(&self.sim.log.lock().unwrap().iter().cloned().collect::<Vec<_>>().join("\n"));
         gtk! {
             <Application::new_unwrap(Some("nl.pepijndevos.mosaic"), ApplicationFlags::empty())>
                 <Window default_width=500 default_height=500
                         border_width=20 on destroy=|_| Message::Exit>
-                    <Layout on draw=|l, cr| { draw_layout(l, cr); Message::None }
+                    <HeaderBar title="Mosaic" show_close_button=true>
+                        <Button label="Open" on clicked=|_| Message::Open/>
+                        <Button label="Save" on clicked=|_| Message::Save/>
+                        <Button label="Op" on clicked=|_| Message::RunAnalysis(AnalysisMode::Op)/>
+                        <Button label="Tran" on clicked=|_| Message::RunAnalysis(AnalysisMode::Tran)/>
+                        <Button label="AC" on clicked=|_| Message::RunAnalysis(AnalysisMode::Ac)/>
+                    </HeaderBar>
+                    <Box orientation=Orientation::Vertical spacing=5>
+                    <Layout on draw={let wires = self.doc.wires.clone();
+                                     move |_, cr| { draw_layout(&wires, cr); Message::None }}
                             /*on motion_notify_event=|l, e| Message::Coord(e.get_coords().unwrap())*/>
-                        <Image Layout::x=220 Layout::y=200 pixbuf=Some(self.nmos.clone())/>
+                        { self.doc.symbols.iter().map(|s| self.render_symbol(s)) }
                         <Image Layout::x=240 Layout::y=220
                         pixbuf={ if vgs < vth {
                             Some(self.open_small.clone())
@@ -185,38 +886,63 @@ impl Component for Model {
                             Some(self.i_small.clone())
                         } }/>
                         <Entry Layout::x=350 Layout::y=220 width_chars=4
-                               text={self.params["m1 w"].clone()}
+                               text={self.doc.params["m1 w"].clone()}
                                on changed=|e| Message::ParamChange(
                                    String::from("m1 w"),
                                    String::from(e.get_text())) />
                         <Entry Layout::x=350 Layout::y=260 width_chars=4
-                               text={self.params["m1 l"].clone()}
+                               text={self.doc.params["m1 l"].clone()}
                                on changed=|e| Message::ParamChange(
                                    String::from("m1 l"),
                                    String::from(e.get_text())) />
                         <Label Layout::x=350 Layout::y=300
-                               text=format!("id={:.3e}", *self.results.get("@m1[id]").unwrap_or(&0.))/>
+                               text=format!("id={:.3e}", self.results.scalar("@m1[id]"))/>
                         <Label Layout::x=350 Layout::y=320
-                               text=format!("gm={:.3e}", *self.results.get("@m1[gm]").unwrap_or(&0.))/>
-                        <Image Layout::x=300 Layout::y=50 pixbuf=Some(self.r.clone())/>
+                               text=format!("gm={:.3e}", self.results.scalar("@m1[gm]"))/>
                         <Entry Layout::x=350 Layout::y=75 width_chars=4
-                               text={self.params["r1"].clone()}
+                               text={self.doc.params["r1"].clone()}
                                on changed=|e| Message::ParamChange(
                                    String::from("r1"),
                                    String::from(e.get_text())) />
-                        <Image Layout::x=20 Layout::y=200 pixbuf=Some(self.v.clone())/>
                         <Entry Layout::x=70 Layout::y=210 width_chars=4
-                               text={self.params["v1 dc"].clone()}
+                               text={self.doc.params["v1 dc"].clone()}
                                on changed=|e| Message::ParamChange(
                                    String::from("v1 dc"),
                                    String::from(e.get_text())) />
-                        <Image Layout::x=100 Layout::y=320 pixbuf=Some(self.v.clone())/>
                         <Entry Layout::x=150 Layout::y=330 width_chars=4
-                               text={self.params["v2 dc"].clone()}
+                               text={self.doc.params["v2 dc"].clone()}
                                on changed=|e| Message::ParamChange(
                                    String::from("v2 dc"),
                                    String::from(e.get_text())) />
                     </Layout>
+                    // Signal picker: a toggle per saved signal of interest.
+                    <Box orientation=Orientation::Horizontal spacing=5>
+                        <CheckButton label="@m1[id]" active=self.plot.iter().any(|s| s == "@m1[id]")
+                                     on toggled=|_| Message::ToggleSignal(String::from("@m1[id]"))/>
+                        <CheckButton label="@m1[gm]" active=self.plot.iter().any(|s| s == "@m1[gm]")
+                                     on toggled=|_| Message::ToggleSignal(String::from("@m1[gm]"))/>
+                    </Box>
+                    // Waveform area, drawn in the same immediate-mode style as the schematic.
+                    <DrawingArea height_request=150
+                                 on draw={let result = self.results.clone();
+                                          let signals = self.plot.clone();
+                                          move |da, cr| {
+                                              let w = da.get_allocated_width() as f64;
+                                              let h = da.get_allocated_height() as f64;
+                                              draw_plot(&result, &signals, cr, w, h);
+                                              Message::None
+                                          }}/>
+                    // ngspice console: newest lines scrolled into view.
+                    <ScrolledWindow height_request=100>
+                        <TextView editable=false cursor_visible=false buffer=Some(&console)
+                                  on size_allocate=|tv, _| {
+                                      let buf = tv.get_buffer().unwrap();
+                                      let mut end = buf.get_end_iter();
+                                      tv.scroll_to_iter(&mut end, 0., false, 0., 0.);
+                                      Message::None
+                                  }/>
+                    </ScrolledWindow>
+                    </Box>
                 </Window>
             </Application>
         }